@@ -1,9 +1,38 @@
 /// Functions for rendering the help window
-use crossterm::style::{StyledContent, Stylize};
-use textwrap::{self, word_splitters::NoHyphenation, Options};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use crossterm::style::{Color, StyledContent, Stylize};
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+use textwrap::{
+    self, word_splitters::NoHyphenation, wrap_algorithms::Penalties, Options, WrapAlgorithm,
+};
 
 const README_STR: &str = include_str!("../../README.md");
 
+/// The style that should be applied to a run of characters in the rendered help text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct TextStyle {
+    bold: bool,
+    italic: bool,
+    /// Set on unhighlighted code block lines (unrecognized language) so they still read as
+    /// distinct from prose.
+    dim: bool,
+    /// Foreground color assigned by the syntax highlighter for a fenced code block.
+    color: Option<Color>,
+}
+
+/// A run of characters (identified by a range of `char` indices into the plain-text buffer
+/// produced by [`render_markdown`]) that should be rendered with a particular [`TextStyle`].
+struct StyleSpan {
+    range: Range<usize>,
+    style: TextStyle,
+}
+
 /// Word-wrap the help string to be displayed in the help window, and apply correct formatting
 /// (such as bolding) using crossterm::style.
 ///
@@ -11,48 +40,133 @@ const README_STR: &str = include_str!("../../README.md");
 /// contains either a single string for the whole line, or multiple strings, if the style varies
 /// within the line.
 pub fn get_formatted_help_text(width: usize) -> Vec<Vec<StyledContent<String>>> {
-    let help_str = &README_STR[
-        README_STR.find("## User guide").expect("Could not find user guide in README")
-        ..
-        README_STR.find("## Similar projects").expect("Could not find end of user guide in README")
-    ];
-
-    // Skip the table of keyboard shortcuts, we'll format it separately
-    let (help_str, rest) = help_str
+    let help_str = &README_STR[README_STR
+        .find("## User guide")
+        .expect("Could not find user guide in README")
+        ..README_STR
+            .find("## Similar projects")
+            .expect("Could not find end of user guide in README")];
+
+    // Render the keyboard shortcuts table separately, since it needs its own column-aware
+    // layout rather than running through the regular markdown wrapping pipeline.
+    let (before_table, after_table) = help_str
         .split_once("\n\n|")
         .expect("Could not find keyboard shortcuts table in readme");
 
-    let rest = rest
+    let after_table = after_table
         .split_once("\n\n")
         .expect("Could not find end of keyboard shortcuts table in readme")
         .1;
 
-    // Add justified keyboard shortcuts table to help string
-    let mut help_str = help_str.to_string();
-    help_str.push_str("\n\n"); // add back newlines eaten by split_once
-    help_str.push_str(&get_justified_keyboard_shortcuts_table());
-    help_str.push_str(rest);
+    let mut lines = render_help_section(before_table, width);
+    lines.extend(get_justified_keyboard_shortcuts_table(width));
+    lines.extend(render_help_section(after_table, width));
+    lines
+}
 
-    // We need to get rid of the `<kbd>` tags before wrapping so it works correctly. We're going to
+/// Run a slice of the user guide through markdown parsing, optimal-fit wrapping, and styling,
+/// producing the lines ready to display in the help window.
+///
+/// Code block ranges are rendered verbatim (split on their existing newlines) rather than being
+/// handed to the word wrapper, since wrapping would break an over-wide code line onto
+/// continuation lines; each code line is instead kept whole and truncated at `width`.
+///
+/// Every segment's starting style-span offset is taken directly from its real position in
+/// `chars` (`pos`/`code_range.start`/`code_range.end`), never from a count of wrapped output
+/// lines: `textwrap::wrap` can both collapse source whitespace and return a phantom `[""]` line
+/// for empty input, so a counter built by accumulating "characters produced + one per line"
+/// across chained calls drifts from the true source offset and misstyles everything after it.
+fn render_help_section(markdown: &str, width: usize) -> Vec<Vec<StyledContent<String>>> {
+    // We need to get rid of the `<kbd>` tags before parsing so it works correctly. We're going to
     // bold all words within backticks, so replace the tags with backticks as well.
-    let help_str = help_str
-        .replace("<kbd>",  "`")
-        .replace("</kbd>", "`");
+    let markdown = markdown.replace("<kbd>", "`").replace("</kbd>", "`");
+
+    // Parse the markdown into plain text plus the style spans needed to reproduce its
+    // formatting, instead of hand-rolled index counting.
+    let (text, style_spans, code_ranges) = render_markdown(&markdown);
+    let chars: Vec<char> = text.chars().collect();
 
-    // Strip out markup and extract the locations where we need to toggle bold on/off.
-    let (help_str, bold_toggle_locs) = strip_markup_and_extract_bold_positions(&help_str);
+    let mut lines = vec![];
+    let mut pos = 0;
 
-    // apply text wrapping
-    let opts = Options::with_word_splitter(width, NoHyphenation);
-    let help_str = textwrap::wrap(&help_str, opts);
+    for code_range in &code_ranges {
+        let (styled, _) = wrap_prose(&chars[pos..code_range.start], width, &style_spans, pos);
+        lines.extend(styled);
+
+        lines.extend(render_code_block_lines(
+            &chars[code_range.clone()],
+            code_range.start,
+            width,
+            &style_spans,
+        ));
+
+        pos = code_range.end;
+    }
+    let (styled, _) = wrap_prose(&chars[pos..], width, &style_spans, pos);
+    lines.extend(styled);
+
+    lines
+}
+
+/// Style and lay out a code block's already-verbatim lines (split on their existing newlines),
+/// truncating any line wider than `width` so a long README example can't overflow the help pane.
+///
+/// `start` is `code_chars`'s own absolute offset into the style-span buffer. Each line's starting
+/// counter is computed from `start` plus the real (pre-truncation) lengths of the lines before
+/// it, not from how many characters made it into the (possibly truncated) display line, so
+/// truncating a line can't desync the spans of the lines that follow it.
+fn render_code_block_lines(
+    code_chars: &[char],
+    start: usize,
+    width: usize,
+    style_spans: &[StyleSpan],
+) -> Vec<Vec<StyledContent<String>>> {
+    let code_text: String = code_chars.iter().collect();
+
+    let mut lines = vec![];
+    let mut counter = start;
+    for line in code_text.split('\n') {
+        let line_len = line.chars().count();
+        let display: String = line.chars().take(width).collect();
+        let (styled, _) = stylize_wrapped_lines(vec![display], style_spans, counter);
+        lines.extend(styled);
+
+        // advance by the line's real length, not the (possibly truncated) display length, plus
+        // the newline consumed between this line and the next
+        counter += line_len + 1;
+    }
 
-    // apply bold at the toggle locations and return
-    stylize_wrapped_lines(help_str, bold_toggle_locs)
+    lines
 }
 
-/// Apply justification to the table of keyboard shortcuts in the README and render it to a String
-/// without the markup
-pub fn get_justified_keyboard_shortcuts_table() -> String {
+/// Word-wrap a run of plain-text characters using optimal-fit (Knuth-Plass) line breaking, so
+/// the help pane doesn't end up with ragged edges and short lines like greedy first-fit wrapping
+/// gives us, then apply styling at the recorded spans.
+fn wrap_prose(
+    chars: &[char],
+    width: usize,
+    style_spans: &[StyleSpan],
+    counter: usize,
+) -> (Vec<Vec<StyledContent<String>>>, usize) {
+    let prose: String = chars.iter().collect();
+    let opts = Options::with_word_splitter(width, NoHyphenation)
+        .wrap_algorithm(WrapAlgorithm::OptimalFit(Penalties::new()));
+    let wrapped = textwrap::wrap(&prose, opts);
+    stylize_wrapped_lines(wrapped, style_spans, counter)
+}
+
+/// Minimum width we're willing to give the shortcut column before giving up on a side-by-side
+/// layout and stacking the shortcut underneath its action instead.
+const MIN_SHORTCUT_COLUMN_WIDTH: usize = 6;
+
+/// Render the table of keyboard shortcuts in the README as a width-aware two-column layout:
+/// action in the left column, shortcut in the right, each wrapped independently to its column
+/// width. On terminals too narrow for two columns, the shortcut is stacked underneath its
+/// action (indented) instead of being squeezed or truncated.
+///
+/// Returns styled lines, with the header row rendered bold, ready to be spliced into the help
+/// text alongside the rest of the user guide.
+pub fn get_justified_keyboard_shortcuts_table(width: usize) -> Vec<Vec<StyledContent<String>>> {
     let keyboard_shortcuts = README_STR
         .split_once("keyboard shortcuts:\n\n")
         .expect("Couldn't find table of keyboard shortcuts in README")
@@ -62,136 +176,397 @@ pub fn get_justified_keyboard_shortcuts_table() -> String {
         .expect("Couldn't find end of keyboard shortcuts table in README")
         .0;
 
-    let first_column_width = keyboard_shortcuts
+    let rows: Vec<(String, String)> = keyboard_shortcuts
         .lines()
-        .map(|line| line.split('|').nth(1).unwrap_or("").len())
-        .max()
-        .unwrap_or(10);
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split('|').collect();
+            // cols[0] is empty, because the lines start with '|'.
+            let action = cols[1].trim().to_string();
+            // skip the markdown table formatting row
+            if action.starts_with(":--") {
+                return None;
+            }
+            let shortcut = cols
+                .get(2)
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default();
+            Some((action, shortcut))
+        })
+        .collect();
 
-    let mut justified = String::new();
+    let mut table = justify_keyboard_shortcuts_rows(&rows, width);
 
-    for (i, line) in keyboard_shortcuts.lines().enumerate() {
-        let cols: Vec<&str> = line.split('|').collect();
-        // cols[0] is empty, because the lines start with '|'.
-        let mut action = cols[1].trim().to_string();
-        let mut shortcut = cols[2].trim().to_string();
+    // add an extra blank line, like the markdown source has between the table and the next
+    // section
+    table.push(vec![]);
+
+    table
+}
 
-        // skip markdown table formatting row
-        if action.starts_with(":--") {
-            continue;
+/// Lay out `rows` (action, shortcut) pairs into a width-aware two-column table, falling back to
+/// stacking the shortcut underneath its action (indented) when `width` is too narrow for a
+/// second column. Split out from [`get_justified_keyboard_shortcuts_table`] so the layout logic
+/// can be exercised directly without going through the README.
+fn justify_keyboard_shortcuts_rows(
+    rows: &[(String, String)],
+    width: usize,
+) -> Vec<Vec<StyledContent<String>>> {
+    let action_column_width = rows
+        .iter()
+        .map(|(action, _)| action.chars().count())
+        .max()
+        .unwrap_or(10)
+        .min(width.saturating_sub(MIN_SHORTCUT_COLUMN_WIDTH + 1).max(1))
+        .min(2 * width / 3);
+
+    let mut table = vec![];
+
+    for (i, (action, shortcut)) in rows.iter().enumerate() {
+        let bold = i == 0;
+        let shortcut_column_width = width.saturating_sub(action_column_width + 1);
+
+        if shortcut_column_width >= MIN_SHORTCUT_COLUMN_WIDTH {
+            let action_lines = textwrap::wrap(action, action_column_width.max(1));
+            let shortcut_lines = textwrap::wrap(shortcut, shortcut_column_width);
+
+            for row in 0..action_lines.len().max(shortcut_lines.len()) {
+                let action_cell = action_lines.get(row).map(|s| s.as_ref()).unwrap_or("");
+                let shortcut_cell = shortcut_lines.get(row).map(|s| s.as_ref()).unwrap_or("");
+                let line = format!(
+                    "{:<width$} {}",
+                    action_cell,
+                    shortcut_cell,
+                    width = action_column_width
+                );
+                table.push(stylize_table_row(line, bold));
+            }
+        } else {
+            // not enough room for two columns: stack the shortcut under its action, indented
+            for line in textwrap::wrap(action, width.max(1)) {
+                table.push(stylize_table_row(line.into_owned(), bold));
+            }
+            for line in textwrap::wrap(shortcut, width.saturating_sub(2).max(1)) {
+                table.push(stylize_table_row(format!("  {line}"), bold));
+            }
         }
+    }
 
-        if i == 0 {
-            // add backticks so that first line is bolded
-            action = format!("`{}`", &action);
-            shortcut = format!("`{}`", &shortcut);
+    table
+}
+
+fn stylize_table_row(line: String, bold: bool) -> Vec<StyledContent<String>> {
+    if bold {
+        vec![line.bold()]
+    } else {
+        vec![line.stylize()]
+    }
+}
+
+/// Walk the markdown event stream for `text` (via `pulldown-cmark`) and produce the plain text
+/// with all markup removed, the style spans needed to re-apply bold/italic formatting after
+/// wrapping, and the character ranges occupied by fenced code blocks. Code block ranges are
+/// rendered verbatim (not reflowed by the word wrapper), since they're already broken into lines
+/// and indentation that would be destroyed by wrapping.
+///
+/// `Tag::Strong` and headings become bold, `Tag::Emphasis` becomes italic, and inline code keeps
+/// the previous behavior of being bolded. Styles nest correctly because we push the style in
+/// effect before each tag onto a stack and restore it when the tag closes.
+fn render_markdown(text: &str) -> (String, Vec<StyleSpan>, Vec<Range<usize>>) {
+    let mut plain_text = String::new();
+    let mut char_count = 0;
+    let mut spans: Vec<StyleSpan> = vec![];
+    let mut code_ranges: Vec<Range<usize>> = vec![];
+    let mut style = TextStyle::default();
+    let mut open_stack: Vec<(usize, TextStyle)> = vec![];
+    let mut in_code_block = false;
+    let mut code_block_lang: Option<String> = None;
+    let mut code_block_buf = String::new();
+    let mut code_block_start = 0;
+
+    macro_rules! push_str {
+        ($s:expr) => {{
+            let s: &str = $s;
+            plain_text.push_str(s);
+            char_count += s.chars().count();
+        }};
+    }
+    macro_rules! push_char {
+        ($c:expr) => {{
+            plain_text.push($c);
+            char_count += 1;
+        }};
+    }
+    macro_rules! open_style {
+        ($field:ident) => {{
+            open_stack.push((char_count, style));
+            style.$field = true;
+        }};
+    }
+    macro_rules! close_style {
+        () => {{
+            let (start, prev_style) = open_stack.pop().expect("unbalanced markdown tag");
+            if style != prev_style && start < char_count {
+                spans.push(StyleSpan {
+                    range: start..char_count,
+                    style,
+                });
+            }
+            style = prev_style;
+        }};
+    }
+
+    for event in Parser::new(text) {
+        match event {
+            Event::Start(Tag::Strong) => open_style!(bold),
+            Event::End(TagEnd::Strong) => close_style!(),
+            Event::Start(Tag::Emphasis) => open_style!(italic),
+            Event::End(TagEnd::Emphasis) => close_style!(),
+            Event::Start(Tag::Heading { .. }) => open_style!(bold),
+            Event::End(TagEnd::Heading(_)) => {
+                close_style!();
+                push_str!("\n\n");
+            }
+            Event::Start(Tag::Item) => push_str!("- "),
+            Event::End(TagEnd::Item) => push_char!('\n'),
+            Event::End(TagEnd::List(_)) => push_char!('\n'),
+            Event::End(TagEnd::Paragraph) | Event::End(TagEnd::BlockQuote(_)) => {
+                push_str!("\n\n")
+            }
+            Event::Code(code) => {
+                let start = char_count;
+                push_str!(&code);
+                spans.push(StyleSpan {
+                    range: start..char_count,
+                    style: TextStyle {
+                        bold: true,
+                        ..style
+                    },
+                });
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                in_code_block = true;
+                code_block_lang = match kind {
+                    CodeBlockKind::Fenced(lang) if !lang.is_empty() => Some(lang.to_string()),
+                    _ => None,
+                };
+                code_block_buf.clear();
+                code_block_start = char_count;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code_block = false;
+                render_code_block(
+                    &code_block_buf,
+                    code_block_lang.as_deref(),
+                    &mut plain_text,
+                    &mut char_count,
+                    &mut spans,
+                );
+                code_ranges.push(code_block_start..char_count);
+            }
+            Event::Text(t) => {
+                if in_code_block {
+                    code_block_buf.push_str(&t);
+                } else {
+                    push_str!(&t);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => push_char!('\n'),
+            _ => {}
         }
+    }
+
+    // Paragraph/heading/blockquote ends always push a "\n\n" separator for whatever follows, but
+    // nothing follows the last one: trim it so the document doesn't end in a dangling blank line.
+    while plain_text.ends_with("\n\n") {
+        plain_text.truncate(plain_text.len() - 2);
+        char_count -= 2;
+    }
+
+    (plain_text, spans, code_ranges)
+}
+
+/// The syntect syntax definitions used to highlight fenced code blocks in the help text.
+fn code_block_syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// The syntect color theme used to highlight fenced code blocks in the help text.
+fn code_block_theme_set() -> &'static ThemeSet {
+    static THEME_SET: OnceLock<ThemeSet> = OnceLock::new();
+    THEME_SET.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Append a fenced code block's raw source (`code`) to `plain_text`/`spans`, bordered so it
+/// stands out from the surrounding prose. When `lang` names a syntax `syntect` recognizes, each
+/// line is colored according to our theme; otherwise the block falls back to a plain dimmed
+/// style.
+fn render_code_block(
+    code: &str,
+    lang: Option<&str>,
+    plain_text: &mut String,
+    char_count: &mut usize,
+    spans: &mut Vec<StyleSpan>,
+) {
+    const BORDER: &str = "\u{2502} "; // "│ "
+
+    macro_rules! push {
+        ($s:expr) => {{
+            let s: &str = $s;
+            plain_text.push_str(s);
+            *char_count += s.chars().count();
+        }};
+    }
+
+    let highlighted = lang.and_then(|lang| highlight_code_lines(code, lang));
 
-        justified.push_str(&action);
-
-        // backticks will be removed, so add extra space for them
-        let extra_len = action.chars().filter(|c| *c == '`').count();
-        let padding = first_column_width + extra_len + 2 - action.len();
-        justified.push_str(&" ".repeat(padding));
-        // It's ok to add "\n" at the end of every line, because the split_once() above has
-        // eaten too many newlines from the end anyway.
-        justified.push_str(&shortcut);
-        justified.push('\n');
-    }
-
-    // add extra newline at end
-    justified.push('\n');
-
-    justified
-}
-
-/// Return a version of `text`, where all markup has been strippeed, and also return a vector of
-/// indices into the returned string where bold should toggle.
-fn strip_markup_and_extract_bold_positions(text: &str) -> (String, Vec<usize>) {
-    let mut bold_toggle_locs: Vec<usize> = vec![];
-    let mut help_string_no_markup = String::new();
-    let mut prev_char: Option<char> = None;
-    let mut parsing_heading = false;
-    let mut counter = 0;
-    for c in text.chars() {
-        if c == '#' {
-            if !parsing_heading {
-                parsing_heading = true;
-                bold_toggle_locs.push(counter);
+    plain_text.push('\n');
+    *char_count += 1;
+
+    if let Some(lines) = highlighted {
+        for line in lines {
+            push!(BORDER);
+            for (style, text) in line {
+                let start = *char_count;
+                push!(&text);
+                spans.push(StyleSpan {
+                    range: start..*char_count,
+                    style,
+                });
             }
-        } else if c == ' ' && parsing_heading && prev_char == Some('#') {
-            // skip space after hashes that indicate heading
-        } else if c == '\n' && parsing_heading {
-            bold_toggle_locs.push(counter);
-            parsing_heading = false;
-            counter += 1;
-            help_string_no_markup.push(c);
-        } else if c == '`' {
-            bold_toggle_locs.push(counter);
-        } else {
-            counter += 1;
-            help_string_no_markup.push(c);
+            push!("\n");
+        }
+    } else {
+        let dim_style = TextStyle {
+            dim: true,
+            ..TextStyle::default()
+        };
+        for line in code.lines() {
+            push!(BORDER);
+            let start = *char_count;
+            push!(line);
+            spans.push(StyleSpan {
+                range: start..*char_count,
+                style: dim_style,
+            });
+            push!("\n");
         }
-        prev_char = Some(c);
     }
+}
 
-    (help_string_no_markup, bold_toggle_locs)
+/// Run `code` through `syntect`'s line highlighter for `lang`, returning per-line runs of
+/// `(style, text)` with each run's foreground color taken from our theme. Returns `None` if
+/// `lang` isn't a syntax `syntect` recognizes.
+fn highlight_code_lines(code: &str, lang: &str) -> Option<Vec<Vec<(TextStyle, String)>>> {
+    let syntax = code_block_syntax_set().find_syntax_by_token(lang)?;
+    let theme = code_block_theme_set().themes.get("base16-ocean.dark")?;
+    let mut highlighter = HighlightLines::new(syntax, theme);
+
+    let mut rendered = vec![];
+    for line in LinesWithEndings::from(code) {
+        let ranges = highlighter
+            .highlight_line(line, code_block_syntax_set())
+            .ok()?;
+        let rendered_line = ranges
+            .into_iter()
+            .map(|(style, text)| {
+                let text = text.trim_end_matches(['\n', '\r']).to_string();
+                let color = Color::Rgb {
+                    r: style.foreground.r,
+                    g: style.foreground.g,
+                    b: style.foreground.b,
+                };
+                (
+                    TextStyle {
+                        color: Some(color),
+                        ..TextStyle::default()
+                    },
+                    text,
+                )
+            })
+            .filter(|(_, text)| !text.is_empty())
+            .collect();
+        rendered.push(rendered_line);
+    }
+    Some(rendered)
 }
 
-/// Apply stylization to the text. Toggle bold at the positions indicated by `bold_toggle_locs`.
+/// Apply stylization to the text, using the style recorded for each character range in `spans`.
 fn stylize_wrapped_lines<S>(
     lines: Vec<S>,
-    bold_toggle_locs: Vec<usize>,
-) -> Vec<Vec<StyledContent<String>>>
+    spans: &[StyleSpan],
+    mut counter: usize,
+) -> (Vec<Vec<StyledContent<String>>>, usize)
 where
     S: AsRef<str>,
 {
-    let mut counter = 0;
-    let mut bold_toggle_locs = bold_toggle_locs.iter();
-    let mut next_toggle_loc = bold_toggle_locs.next();
     let mut res = vec![];
-    let mut bold = false;
 
     for line in lines {
         let mut line_chunks = vec![];
         let mut cur_chunk = String::new();
+        let mut cur_style: Option<TextStyle> = None;
 
         for c in line.as_ref().chars() {
-            if Some(&counter) == next_toggle_loc {
-                line_chunks.push(if bold {
-                    cur_chunk.bold()
-                } else {
-                    cur_chunk.stylize()
-                });
-                bold = !bold;
-                next_toggle_loc = bold_toggle_locs.next();
-                cur_chunk = String::new();
+            let style = style_at(spans, counter);
+            if Some(style) != cur_style {
+                if let Some(prev_style) = cur_style {
+                    line_chunks.push(stylize_chunk(cur_chunk, prev_style));
+                    cur_chunk = String::new();
+                }
+                cur_style = Some(style);
             }
             cur_chunk.push(c);
             counter += 1;
         }
 
-        if !cur_chunk.is_empty() {
-            line_chunks.push(if bold {
-                cur_chunk.bold()
-            } else {
-                cur_chunk.stylize()
-            });
-        }
-
-        // always turn off bold at the end of the line
-        if bold {
-            bold = false;
-            next_toggle_loc = bold_toggle_locs.next();
+        if let Some(style) = cur_style {
+            if !cur_chunk.is_empty() {
+                line_chunks.push(stylize_chunk(cur_chunk, style));
+            }
         }
 
         res.push(line_chunks);
 
-        // increment counter for newline
+        // increment counter for the newline eaten by wrapping
         counter += 1;
     }
 
-    res
+    (res, counter)
+}
+
+/// Determine the style in effect at character index `pos`, by combining every span that covers
+/// it. Spans are small in number (a handful of README formatting runs), so a linear scan is fine.
+fn style_at(spans: &[StyleSpan], pos: usize) -> TextStyle {
+    let mut style = TextStyle::default();
+    for span in spans {
+        if span.range.contains(&pos) {
+            style.bold |= span.style.bold;
+            style.italic |= span.style.italic;
+            style.dim |= span.style.dim;
+            if span.style.color.is_some() {
+                style.color = span.style.color;
+            }
+        }
+    }
+    style
+}
+
+fn stylize_chunk(chunk: String, style: TextStyle) -> StyledContent<String> {
+    let mut styled = chunk.stylize();
+    if style.bold {
+        styled = styled.bold();
+    }
+    if style.italic {
+        styled = styled.italic();
+    }
+    if style.dim {
+        styled = styled.dim();
+    }
+    if let Some(color) = style.color {
+        styled = styled.with(color);
+    }
+    styled
 }
 
 #[cfg(test)]
@@ -205,22 +580,185 @@ mod tests {
     }
 
     #[test]
-    fn test_strip_markup() {
+    fn test_render_markdown_strips_markup() {
         let input = "## foo bar\n\nlorem ipsum `dolor` sit amet";
-        let (output, locs) = strip_markup_and_extract_bold_positions(input);
+        let (output, spans, _code_ranges) = render_markdown(input);
         assert_eq!(output, "foo bar\n\nlorem ipsum dolor sit amet");
-        assert_eq!(locs, vec![0, 7, 21, 26]);
+
+        let bold: Vec<String> = spans
+            .iter()
+            .filter(|span| span.style.bold && !span.style.italic)
+            .map(|span| {
+                output
+                    .chars()
+                    .skip(span.range.start)
+                    .take(span.range.len())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(bold, vec!["foo bar".to_string(), "dolor".to_string()]);
     }
 
     #[test]
-    fn test_stylize_wrapped_lines() {
-        let lines = vec!["foo bar", "", "lorem ipsum dolor sit amet"];
-        let stylized = stylize_wrapped_lines(lines, vec![0, 7, 21, 26]);
+    fn test_render_markdown_emphasis_and_strong() {
+        let input = "plain *italic* and **bold** text";
+        let (output, spans, _code_ranges) = render_markdown(input);
+        assert_eq!(output, "plain italic and bold text");
+        assert!(spans
+            .iter()
+            .any(|span| span.style.italic && !span.style.bold));
+        assert!(spans
+            .iter()
+            .any(|span| span.style.bold && !span.style.italic));
+    }
+
+    #[test]
+    fn test_render_markdown_code_block_known_language_is_colored() {
+        let input = "```rust\nfn main() {}\n```";
+        let (output, spans, _code_ranges) = render_markdown(input);
+        assert!(output.contains("fn main() {}"));
+        assert!(spans.iter().any(|span| span.style.color.is_some()));
+    }
+
+    #[test]
+    fn test_render_markdown_code_block_unknown_language_is_dimmed() {
+        let input = "```notalang\nfoo bar\n```";
+        let (output, spans, _code_ranges) = render_markdown(input);
+        assert!(output.contains("foo bar"));
+        assert!(spans.iter().any(|span| span.style.dim));
+    }
+
+    #[test]
+    fn test_render_markdown_reports_code_block_range() {
+        let input = "intro\n\n```notalang\nfirst line\nsecond line\n```\n\nafter";
+        let (output, _spans, code_ranges) = render_markdown(input);
+        assert_eq!(code_ranges.len(), 1);
+
+        let code: String = output
+            .chars()
+            .skip(code_ranges[0].start)
+            .take(code_ranges[0].len())
+            .collect();
+        // exactly one blank line before the block and one after, not a substring match, so a
+        // regression that adds or drops a separating newline doesn't slip past the test
+        assert_eq!(code, "\n\u{2502} first line\n\u{2502} second line\n");
+    }
+
+    #[test]
+    fn test_render_help_section_does_not_reflow_code_blocks() {
+        let markdown = "intro text\n\n```notalang\nfirst line\nsecond line\n```\n\nafter text";
+        let lines = render_help_section(markdown, 80);
+        let plain_lines: Vec<String> = lines
+            .iter()
+            .map(|chunks| chunks.iter().map(|c| c.content().clone()).collect())
+            .collect();
+
+        // the code block should contribute exactly one blank line on each side, not two after
+        let code_start = plain_lines
+            .iter()
+            .position(|line| line == "\u{2502} first line")
+            .expect("first code line not found");
+        assert_eq!(plain_lines[code_start - 1], "");
+        assert_eq!(plain_lines[code_start + 1], "\u{2502} second line");
+        assert_eq!(plain_lines[code_start + 2], "");
+        assert_ne!(plain_lines[code_start + 3], "");
+    }
+
+    #[test]
+    fn test_render_help_section_styles_text_after_code_block() {
+        // the counter must stay in sync with the real character offset past a code block, or
+        // this bold run would pick up the wrong style (or none at all)
+        let markdown = "```notalang\ncode\n```\n\nthis is **bold** after the block";
+        let lines = render_help_section(markdown, 80);
+
+        let bold_chunk = lines
+            .iter()
+            .flatten()
+            .find(|chunk| chunk.content() == "bold")
+            .expect("expected a \"bold\" chunk after the code block");
+        assert_eq!(*bold_chunk, "bold".to_string().bold());
+    }
+
+    #[test]
+    fn test_render_code_block_lines_truncates_overlong_lines() {
+        let code_chars: Vec<char> = "abcdefghij".chars().collect();
+        let lines = render_code_block_lines(&code_chars, 0, 5, &[]);
+        let plain_lines: Vec<String> = lines
+            .iter()
+            .map(|chunks| chunks.iter().map(|c| c.content().clone()).collect())
+            .collect();
+
+        assert_eq!(plain_lines, vec!["abcde".to_string()]);
+    }
 
+    #[test]
+    fn test_justify_keyboard_shortcuts_rows_uses_two_columns_at_generous_width() {
+        let rows = vec![
+            ("Move cursor up".to_string(), "Up / k".to_string()),
+            ("Move cursor down".to_string(), "Down / j".to_string()),
+        ];
+        let table = justify_keyboard_shortcuts_rows(&rows, 40);
+        let plain_lines: Vec<String> = table
+            .iter()
+            .map(|chunks| chunks.iter().map(|c| c.content().clone()).collect())
+            .collect();
+
+        // each row fits on a single side-by-side line, with the shortcut starting right after
+        // the padded action column rather than stacked on its own indented line
         assert_eq!(
-            stylized[0],
-            vec!["".to_string().stylize(), "foo bar".to_string().bold()]
+            plain_lines,
+            vec![
+                "Move cursor up   Up / k".to_string(),
+                "Move cursor down Down / j".to_string(),
+            ]
         );
+    }
+
+    #[test]
+    fn test_justify_keyboard_shortcuts_rows_stacks_shortcut_at_narrow_width() {
+        let rows = vec![("Move cursor up".to_string(), "Up / k".to_string())];
+        let table = justify_keyboard_shortcuts_rows(&rows, MIN_SHORTCUT_COLUMN_WIDTH);
+        let plain_lines: Vec<String> = table
+            .iter()
+            .map(|chunks| chunks.iter().map(|c| c.content().clone()).collect())
+            .collect();
+
+        // too narrow for a second column: the shortcut is stacked on its own indented line
+        // underneath the action, never truncated or squeezed onto the same line
+        assert_eq!(
+            plain_lines,
+            vec![
+                "Move".to_string(),
+                "cursor".to_string(),
+                "up".to_string(),
+                "  Up /".to_string(),
+                "  k".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stylize_wrapped_lines() {
+        let lines = vec!["foo bar", "", "lorem ipsum dolor sit amet"];
+        let spans = vec![
+            StyleSpan {
+                range: 0..7,
+                style: TextStyle {
+                    bold: true,
+                    ..TextStyle::default()
+                },
+            },
+            StyleSpan {
+                range: 21..26,
+                style: TextStyle {
+                    bold: true,
+                    ..TextStyle::default()
+                },
+            },
+        ];
+        let (stylized, _) = stylize_wrapped_lines(lines, &spans, 0);
+
+        assert_eq!(stylized[0], vec!["foo bar".to_string().bold()]);
         assert_eq!(stylized[1], vec![]);
         assert_eq!(stylized[2][0], "lorem ipsum ".to_string().stylize());
         assert_eq!(stylized[2][1], "dolor".to_string().bold());